@@ -8,17 +8,32 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use graphviz::{self, Labeller, GraphWalk};
+use graphviz::{self, Labeller, GraphWalk, Style};
 
 use rustc::middle::ty;
 use rustc_trans::save::{self, SaveContext};
 
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs::File;
 use std::iter::FromIterator;
 
 use syntax::ast::NodeId;
-use syntax::{ast, visit};
+use syntax::codemap::Span;
+use syntax::{ast, attr, visit};
+
+// Attributes used to assert that a call path does (or does not) exist
+// between two annotated functions, modelled on rustc's `assert_dep_graph`
+// pass (`#[rustc_if_this_changed]` / `#[rustc_then_this_would_need]`).
+const IF_THIS_CHANGED: &'static str = "callgraph_if_this_changed";
+const THEN_THIS_WOULD_NEED: &'static str = "callgraph_then_this_would_need";
+
+// Environment variable holding a `;`-separated list of `caller -> callee`
+// substring filters, modelled on rustc's `RUST_FORBID_DEP_GRAPH_EDGE`. Any
+// call edge whose caller qualname contains `caller` and whose callee
+// qualname contains `callee` is reported as an architectural violation.
+const FORBID_EDGE_ENV: &'static str = "CALLGRAPH_FORBID_EDGE";
 
 
 // Records functions and function calls, processes and outputs this data.
@@ -38,8 +53,49 @@ pub struct RecordVisitor<'l, 'tcx: 'l> {
     // Maps a method decl to its implementing methods.
     method_impls: HashMap<NodeId, Vec<NodeId>>,
 
+    // Functions/methods tagged #[callgraph_if_this_changed].
+    changed_sources: HashSet<NodeId>,
+    // Functions/methods tagged #[callgraph_then_this_would_need].
+    changed_targets: HashSet<NodeId>,
+
+    // (caller_substr, callee_substr) filters parsed from FORBID_EDGE_ENV.
+    forbidden_edges: Vec<(String, String)>,
+
+    // Entry points for the reachability/dead-code analysis: `main`, plus any
+    // `pub fn`/`pub` method.
+    roots: HashSet<NodeId>,
+    // Whether `dot()` omits functions the dead-code analysis finds
+    // unreachable from any root (and the edges touching them). When false,
+    // such functions are still drawn, but dimmed (gray).
+    hide_dead_code: bool,
+
+    // Calls whose span originates in macro-generated code, attributed back
+    // to the function containing the macro invocation.
+    macro_calls: HashSet<(NodeId, NodeId)>,
+    // Whether to recover functions/methods/calls from macro-generated code at
+    // all, rather than silently dropping them by default.
+    track_macro_calls: bool,
+
+    // Call span for each static/dynamic/macro edge, keyed the same way as
+    // static_calls/dynamic_calls/macro_calls at the time the edge was
+    // recorded (i.e. before post_process resolves a dynamic/macro edge's
+    // decl target to its implementations). Used to locate forbidden-edge
+    // violations reported by post_process, which then rekeys it to the
+    // resolved edges.
+    call_spans: HashMap<(NodeId, NodeId), Span>,
+
     // Which function we're calling from, we'll update this as we walk the AST.
     cur_fn: Option<NodeId>,
+
+    // Memoised result of `cycle_nodes()`. The `Labeller` callbacks that use
+    // it run once per node while rendering, so recomputing the SCCs from
+    // scratch each time would turn a single `dot()` call into an O(N*(V+E))
+    // operation.
+    cycle_nodes_cache: RefCell<Option<HashSet<NodeId>>>,
+    // Memoised result of `reachable_all()`, for the same reason: the
+    // `Labeller` impl calls it once per node, and the `GraphWalk` impl from
+    // both `nodes()` and `edges()`.
+    reachable_cache: RefCell<Option<HashSet<NodeId>>>,
 }
 
 // `this.cur_fn.is_some()` or returns.
@@ -60,13 +116,6 @@ macro_rules! push_walk_pop {($this: expr, $id: expr, $walk: expr) => {{
     $this.cur_fn = prev_fn;
 }}}
 
-// Return if we're in generated code.
-macro_rules! skip_generated_code {($span: expr) => {
-    if save::generated_code($span) {
-        return;
-    }
-}}
-
 // True if the def_id refers to an item in the current crate.
 fn is_local(id: ast::DefId) -> bool {
     id.krate == ast::LOCAL_CRATE
@@ -84,10 +133,67 @@ impl<'l, 'tcx: 'l> RecordVisitor<'l, 'tcx> {
             method_decls: HashMap::new(),
             method_impls: HashMap::new(),
 
+            changed_sources: HashSet::new(),
+            changed_targets: HashSet::new(),
+
+            forbidden_edges: Self::parse_forbidden_edges(),
+
+            roots: HashSet::new(),
+            hide_dead_code: false,
+
+            macro_calls: HashSet::new(),
+            track_macro_calls: false,
+
+            call_spans: HashMap::new(),
+
             cur_fn: None,
+
+            cycle_nodes_cache: RefCell::new(None),
+            reachable_cache: RefCell::new(None),
         }
     }
 
+    // Omit (true) or dim (false, the default) unreachable (dead) functions in
+    // subsequent `dot()` calls.
+    pub fn set_hide_dead_code(&mut self, hide: bool) {
+        self.hide_dead_code = hide;
+    }
+
+    // Opt in to recovering calls whose span lies in macro-generated code,
+    // instead of silently dropping them.
+    pub fn set_track_macro_calls(&mut self, track: bool) {
+        self.track_macro_calls = track;
+    }
+
+    // Parse FORBID_EDGE_ENV into a list of (caller_substr, callee_substr)
+    // filters. Malformed entries (missing `->`) are ignored.
+    fn parse_forbidden_edges() -> Vec<(String, String)> {
+        let raw = match env::var(FORBID_EDGE_ENV) {
+            Ok(raw) => raw,
+            Err(_) => return vec![],
+        };
+
+        raw.split(';')
+           .filter_map(|filter| {
+               let filter = filter.trim();
+               if filter.is_empty() {
+                   return None;
+               }
+
+               let mut parts = filter.splitn(2, "->");
+               let caller = match parts.next() {
+                   Some(caller) => caller.trim().to_string(),
+                   None => return None,
+               };
+               let callee = match parts.next() {
+                   Some(callee) => callee.trim().to_string(),
+                   None => return None,
+               };
+               Some((caller, callee))
+           })
+           .collect()
+    }
+
     // Dump collected and processed information to stdout.
     // Must be called after post_process.
     pub fn dump(&self) {
@@ -128,32 +234,157 @@ impl<'l, 'tcx: 'l> RecordVisitor<'l, 'tcx> {
     // to a call to every method implementing the decl.
     pub fn post_process(&mut self) {
         let mut processed_calls = HashSet::new();
+        let mut processed_spans = HashMap::new();
 
         for &(ref from, ref to) in self.dynamic_calls.iter() {
+            let span = self.call_spans[&(*from, *to)];
             for to in self.method_impls[to].iter() {
                 processed_calls.insert((*from, *to));
+                processed_spans.insert((*from, *to), span);
+            }
+        }
+
+        // A macro-recovered edge can target either a concrete function (the
+        // macro expanded to a static call) or a method decl (the macro
+        // expanded to a dynamically dispatched call) -- resolve the latter
+        // to its implementations, just like `dynamic_calls` above, so no
+        // decl ids are left dangling in the graph.
+        let mut processed_macro_calls = HashSet::new();
+        for &(from, to) in self.macro_calls.iter() {
+            let span = self.call_spans[&(from, to)];
+            match self.method_impls.get(&to) {
+                Some(impls) => {
+                    for &impl_id in impls.iter() {
+                        processed_macro_calls.insert((from, impl_id));
+                        processed_spans.insert((from, impl_id), span);
+                    }
+                }
+                None => {
+                    processed_macro_calls.insert((from, to));
+                    processed_spans.insert((from, to), span);
+                }
+            }
+        }
+
+        // Static edges are recorded as soon as they're seen, which can be
+        // before the callee (e.g. a function defined later in the same
+        // file) has been visited and added to `self.functions` -- so the
+        // forbidden-edge filter can't run reliably until the whole crate has
+        // been walked. Dynamic and macro-recovered edges aren't fully
+        // resolved to concrete targets until now either. So all three are
+        // checked here, together.
+        for &(from, to) in self.static_calls.iter() {
+            if let Some((from_name, to_name)) = self.forbidden_match(from, to) {
+                let span = self.call_spans.get(&(from, to));
+                println!("ERROR: forbidden call edge {} -> {} at {:?}",
+                         from_name, to_name, span);
+            }
+        }
+        for &(from, to) in processed_calls.iter() {
+            if let Some((from_name, to_name)) = self.forbidden_match(from, to) {
+                let span = processed_spans.get(&(from, to));
+                println!("ERROR: forbidden call edge {} -> {} (via dynamic dispatch) at {:?}",
+                         from_name, to_name, span);
+            }
+        }
+        for &(from, to) in processed_macro_calls.iter() {
+            if let Some((from_name, to_name)) = self.forbidden_match(from, to) {
+                let span = processed_spans.get(&(from, to));
+                println!("ERROR: forbidden call edge {} -> {} (via macro expansion) at {:?}",
+                         from_name, to_name, span);
             }
         }
 
         self.dynamic_calls = processed_calls;
+        self.macro_calls = processed_macro_calls;
+
+        // Rekey call_spans to match: static edges keep their existing spans,
+        // dynamic/macro edges pick up the spans resolved above (the old
+        // decl-keyed entries no longer correspond to any edge and are
+        // dropped).
+        let mut new_spans = HashMap::new();
+        for &(from, to) in self.static_calls.iter() {
+            if let Some(&span) = self.call_spans.get(&(from, to)) {
+                new_spans.insert((from, to), span);
+            }
+        }
+        new_spans.extend(processed_spans);
+        self.call_spans = new_spans;
     }
 
-    // Helper function. Record a method call.
-    fn record_method_call(&mut self, mrd: &save::MethodCallData) {
+    // Helper function. Record a method call. `generated` is true when `mrd`
+    // was produced by a macro expansion (see `track_macro_calls`).
+    fn record_method_call(&mut self, mrd: &save::MethodCallData, generated: bool) {
         ensure_cur_fn!(self, mrd.span);
+        let from = self.cur_fn.unwrap();
 
         if let Some(ref_id) = mrd.ref_id {
             if is_local(ref_id) {
-                self.static_calls.insert((self.cur_fn.unwrap(), ref_id.node));
+                let to = ref_id.node;
+                if generated {
+                    self.record_macro_call(from, to, mrd.span);
+                } else {
+                    self.static_calls.insert((from, to));
+                    self.call_spans.insert((from, to), mrd.span);
+                }
             }
             return;
         }
 
         if let Some(decl_id) = mrd.decl_id {
             if is_local(decl_id) {
-                self.dynamic_calls.insert((self.cur_fn.unwrap(), decl_id.node));
+                if generated {
+                    self.record_macro_call(from, decl_id.node, mrd.span);
+                } else {
+                    self.dynamic_calls.insert((from, decl_id.node));
+                    self.call_spans.insert((from, decl_id.node), mrd.span);
+                }
+            }
+        }
+    }
+
+    // Record an edge recovered from macro-generated code, attributing it to
+    // the function containing the macro invocation.
+    fn record_macro_call(&mut self, from: NodeId, to: NodeId, span: Span) {
+        self.macro_calls.insert((from, to));
+        self.call_spans.insert((from, to), span);
+        let invocation_site = Self::macro_invocation_site(span);
+        println!("recovered macro-generated call at {:?} (invoked from {:?})",
+                 span, invocation_site);
+    }
+
+    // Walk `span`'s expansion backtrace to the site of the macro invocation
+    // that produced it.
+    fn macro_invocation_site(span: Span) -> Span {
+        match span.macro_backtrace().first() {
+            Some(frame) => frame.call_site,
+            None => span,
+        }
+    }
+
+    // If the edge (from, to) matches a FORBID_EDGE_ENV filter, return the
+    // qualnames that matched.
+    fn forbidden_match(&self, from: NodeId, to: NodeId) -> Option<(&str, &str)> {
+        if self.forbidden_edges.is_empty() {
+            return None;
+        }
+
+        let from_name = match self.functions.get(&from) {
+            Some(name) => &name[..],
+            None => return None,
+        };
+        let to_name = match self.functions.get(&to) {
+            Some(name) => &name[..],
+            None => return None,
+        };
+
+        for &(ref caller_substr, ref callee_substr) in self.forbidden_edges.iter() {
+            if from_name.contains(&caller_substr[..]) && to_name.contains(&callee_substr[..]) {
+                return Some((from_name, to_name));
             }
         }
+
+        None
     }
 
     // Record that def implements decl.
@@ -164,6 +395,237 @@ impl<'l, 'tcx: 'l> RecordVisitor<'l, 'tcx> {
 
         self.method_impls.get_mut(&decl).unwrap().push(def);
     }
+
+    // If `attrs` carries the path-assertion attributes, remember `id` as a
+    // source and/or target for `check_paths`.
+    fn record_path_assertions(&mut self, id: NodeId, attrs: &[ast::Attribute]) {
+        if attr::contains_name(attrs, IF_THIS_CHANGED) {
+            self.changed_sources.insert(id);
+        }
+        if attr::contains_name(attrs, THEN_THIS_WOULD_NEED) {
+            self.changed_targets.insert(id);
+        }
+    }
+
+    // Build an adjacency map (caller -> callees) from the union of the
+    // static, dynamic, and macro-recovered call edges. Used by the graph
+    // analyses below. Must be called after post_process, since `macro_calls`
+    // (like `dynamic_calls`) holds decl ids until then.
+    fn build_adjacency(&self) -> HashMap<NodeId, Vec<NodeId>> {
+        let mut adjacency = HashMap::new();
+        for &(from, to) in self.static_calls.iter()
+                               .chain(self.dynamic_calls.iter())
+                               .chain(self.macro_calls.iter()) {
+            adjacency.entry(from).or_insert_with(Vec::new).push(to);
+        }
+        adjacency
+    }
+
+    // The set of nodes reachable from `start` (inclusive), following `adjacency`.
+    // Uses an explicit worklist and a visited set so cycles terminate.
+    fn reachable_from(start: NodeId, adjacency: &HashMap<NodeId, Vec<NodeId>>) -> HashSet<NodeId> {
+        let mut visited = HashSet::new();
+        let mut worklist = vec![start];
+        visited.insert(start);
+
+        while let Some(node) = worklist.pop() {
+            if let Some(successors) = adjacency.get(&node) {
+                for &succ in successors {
+                    if visited.insert(succ) {
+                        worklist.push(succ);
+                    }
+                }
+            }
+        }
+
+        visited
+    }
+
+    // Report, for every (source, target) pair tagged with
+    // #[callgraph_if_this_changed] / #[callgraph_then_this_would_need],
+    // whether a call path exists from source to target.
+    // Must be called after post_process.
+    pub fn check_paths(&self) {
+        let adjacency = self.build_adjacency();
+
+        for &source in self.changed_sources.iter() {
+            if !self.functions.contains_key(&source) {
+                continue;
+            }
+
+            // A self-loop is a trivial path.
+            let reachable = Self::reachable_from(source, &adjacency);
+
+            for &target in self.changed_targets.iter() {
+                if !self.functions.contains_key(&target) {
+                    continue;
+                }
+
+                let from = &self.functions[&source];
+                let to = &self.functions[&target];
+                if reachable.contains(&target) {
+                    println!("path exists: {} -> {}", from, to);
+                } else {
+                    println!("NO path from {} to {}", from, to);
+                }
+            }
+        }
+    }
+
+    // Find strongly-connected components of size > 1, plus single nodes with
+    // a self-edge; these are exactly the recursive/mutually-recursive cycles
+    // in the call graph. Uses Tarjan's algorithm over an explicit work-list
+    // (rather than native recursion) so large crates don't blow the stack.
+    fn compute_cycles(&self) -> Vec<Vec<NodeId>> {
+        let adjacency = self.build_adjacency();
+        let no_successors: Vec<NodeId> = Vec::new();
+
+        let mut counter = 0;
+        let mut index: HashMap<NodeId, usize> = HashMap::new();
+        let mut lowlink: HashMap<NodeId, usize> = HashMap::new();
+        let mut on_stack: HashSet<NodeId> = HashSet::new();
+        let mut stack: Vec<NodeId> = Vec::new();
+        let mut sccs: Vec<Vec<NodeId>> = Vec::new();
+
+        for &root in self.functions.keys() {
+            if index.contains_key(&root) {
+                continue;
+            }
+
+            // Each frame is (node, index of the next successor to examine).
+            let mut work: Vec<(NodeId, usize)> = vec![(root, 0)];
+
+            while let Some(&(node, pos)) = work.last() {
+                if pos == 0 {
+                    index.insert(node, counter);
+                    lowlink.insert(node, counter);
+                    counter += 1;
+                    stack.push(node);
+                    on_stack.insert(node);
+                }
+
+                let successors = adjacency.get(&node).unwrap_or(&no_successors);
+                if pos < successors.len() {
+                    let succ = successors[pos];
+                    work.last_mut().unwrap().1 = pos + 1;
+
+                    if !index.contains_key(&succ) {
+                        // Tree edge: recurse (push a new frame).
+                        work.push((succ, 0));
+                    } else if on_stack.contains(&succ) {
+                        // Back edge to a node on the current stack.
+                        let succ_index = index[&succ];
+                        if succ_index < lowlink[&node] {
+                            lowlink.insert(node, succ_index);
+                        }
+                    }
+                    // Otherwise it's a cross edge to an already-closed SCC; ignore.
+                } else {
+                    work.pop();
+
+                    if let Some(&(parent, _)) = work.last() {
+                        let node_lowlink = lowlink[&node];
+                        if node_lowlink < lowlink[&parent] {
+                            lowlink.insert(parent, node_lowlink);
+                        }
+                    }
+
+                    if lowlink[&node] == index[&node] {
+                        let mut scc = Vec::new();
+                        loop {
+                            let member = stack.pop().unwrap();
+                            on_stack.remove(&member);
+                            scc.push(member);
+                            if member == node {
+                                break;
+                            }
+                        }
+                        sccs.push(scc);
+                    }
+                }
+            }
+        }
+
+        sccs.into_iter()
+            .filter(|scc| {
+                scc.len() > 1 ||
+                adjacency.get(&scc[0]).map_or(false, |succs| succs.contains(&scc[0]))
+            })
+            .collect()
+    }
+
+    // The set of all NodeIds which are part of some cycle (recursive or
+    // mutually recursive). Used to highlight cycles in the DOT output.
+    // Memoised in `cycle_nodes_cache`, since the `Labeller` impl calls this
+    // once per node while rendering.
+    fn cycle_nodes(&self) -> HashSet<NodeId> {
+        if let Some(ref nodes) = *self.cycle_nodes_cache.borrow() {
+            return nodes.clone();
+        }
+
+        let nodes: HashSet<NodeId> =
+            self.compute_cycles().into_iter().flat_map(|scc| scc.into_iter()).collect();
+        *self.cycle_nodes_cache.borrow_mut() = Some(nodes.clone());
+        nodes
+    }
+
+    // Report every recursive or mutually-recursive cycle in the call graph.
+    // Must be called after post_process.
+    pub fn cycles(&self) {
+        for cycle in self.compute_cycles() {
+            let names: Vec<&str> = cycle.iter().map(|id| &self.functions[id][..]).collect();
+            println!("cycle: {}", names.join(" -> "));
+        }
+    }
+
+    // The set of functions transitively reachable from `self.roots`, found by
+    // a multi-source worklist search over the combined static/dynamic/macro
+    // call edges (see `build_adjacency`).
+    // Memoised in `reachable_cache`, since the `Labeller` impl calls this
+    // once per node while rendering, and the `GraphWalk` impl from both
+    // `nodes()` and `edges()`.
+    fn reachable_all(&self) -> HashSet<NodeId> {
+        if let Some(ref reachable) = *self.reachable_cache.borrow() {
+            return reachable.clone();
+        }
+
+        let adjacency = self.build_adjacency();
+        let mut visited = HashSet::new();
+        let mut worklist = Vec::new();
+
+        for &root in self.roots.iter() {
+            if self.functions.contains_key(&root) && visited.insert(root) {
+                worklist.push(root);
+            }
+        }
+
+        while let Some(node) = worklist.pop() {
+            if let Some(successors) = adjacency.get(&node) {
+                for &succ in successors {
+                    if visited.insert(succ) {
+                        worklist.push(succ);
+                    }
+                }
+            }
+        }
+
+        *self.reachable_cache.borrow_mut() = Some(visited.clone());
+        visited
+    }
+
+    // Report every function that is not transitively reachable from any root
+    // (`main`, or a `pub` function/method) as potential dead code.
+    // Must be called after post_process.
+    pub fn dead_code(&self) {
+        let reachable = self.reachable_all();
+
+        println!("Dead code (unreachable from any root):");
+        for (id, name) in self.functions.iter() {
+            if !reachable.contains(id) {
+                println!("{}", name);
+            }
+        }
+    }
 }
 
 
@@ -181,18 +643,27 @@ impl<'l, 'tcx: 'l> RecordVisitor<'l, 'tcx> {
 impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
     // Visit a path - the path could point to a function or method.
     fn visit_path(&mut self, path: &'v ast::Path, id: NodeId) {
-        skip_generated_code!(path.span);
+        let generated = save::generated_code(path.span);
+        if generated && !self.track_macro_calls {
+            return;
+        }
 
         let data = self.save_cx.get_path_data(id, path);
         if let save::Data::FunctionCallData(ref fcd) = data {
             if is_local(fcd.ref_id) {
                 let to = fcd.ref_id.node;
                 ensure_cur_fn!(self, fcd.span);
-                self.static_calls.insert((self.cur_fn.unwrap(), to));
+                let from = self.cur_fn.unwrap();
+                if generated {
+                    self.record_macro_call(from, to, fcd.span);
+                } else {
+                    self.static_calls.insert((from, to));
+                    self.call_spans.insert((from, to), fcd.span);
+                }
             }
         }
         if let save::Data::MethodCallData(ref mrd) = data {
-            self.record_method_call(mrd);
+            self.record_method_call(mrd, generated);
         }
 
         // Continue walking the AST.
@@ -201,7 +672,10 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
 
     // Visit an expression
     fn visit_expr(&mut self, ex: &'v ast::Expr) {
-        skip_generated_code!(ex.span);
+        let generated = save::generated_code(ex.span);
+        if generated && !self.track_macro_calls {
+            return;
+        }
 
         visit::walk_expr(self, ex);
 
@@ -213,17 +687,28 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
 
         let data = self.save_cx.get_expr_data(ex);
         if let Some(save::Data::MethodCallData(ref mrd)) = data {
-            self.record_method_call(mrd);
+            self.record_method_call(mrd, generated);
         }
     }
 
     fn visit_item(&mut self, item: &'v ast::Item) {
-        skip_generated_code!(item.span);
+        // Functions can themselves be produced by a macro expansion (e.g. a
+        // `macro_rules!`-generated `fn`, or a derive); track those too when
+        // `track_macro_calls` is set, rather than dropping them outright.
+        if save::generated_code(item.span) && !self.track_macro_calls {
+            return;
+        }
 
         if let ast::Item_::ItemFn(..) = item.node {
             let data = self.save_cx.get_item_data(item);
             if let save::Data::FunctionData(fd) = data {
                 self.functions.insert(fd.id, fd.qualname);
+                self.record_path_assertions(fd.id, &item.attrs);
+
+                // Entry points: `main`, or anything `pub`.
+                if item.ident.name.as_str() == "main" || item.vis == ast::Visibility::Public {
+                    self.roots.insert(fd.id);
+                }
 
                 push_walk_pop!(self, fd.id, visit::walk_item(self, item));
 
@@ -235,7 +720,10 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
     }
 
     fn visit_trait_item(&mut self, ti: &'v ast::TraitItem) {
-        skip_generated_code!(ti.span);
+        // See the comment in `visit_item`: trait methods can be macro-generated too.
+        if save::generated_code(ti.span) && !self.track_macro_calls {
+            return;
+        }
 
         // Note to self: it is kinda sucky we have to examine the AST before
         // asking for data here.
@@ -254,7 +742,8 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
                 self.method_decls.insert(fd.id, fd.qualname.clone());
                 self.functions.insert(fd.id, fd.qualname);
                 self.append_method_impl(fd.id, fd.id);
-                
+                self.record_path_assertions(fd.id, &ti.attrs);
+
                 push_walk_pop!(self, fd.id, visit::walk_trait_item(self, ti));
 
                 return;
@@ -266,12 +755,22 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
     }
 
     fn visit_impl_item(&mut self, ii: &'v ast::ImplItem) {
-        skip_generated_code!(ii.span);
+        // See the comment in `visit_item`: impl methods can be macro-generated too.
+        if save::generated_code(ii.span) && !self.track_macro_calls {
+            return;
+        }
 
         if let ast::ImplItem_::MethodImplItem(..) = ii.node {
             let fd = self.save_cx.get_method_data(ii.id, ii.ident.name, ii.span);
             // Record the method's existence.
             self.functions.insert(fd.id, fd.qualname);
+            self.record_path_assertions(fd.id, &ii.attrs);
+
+            // A pub method is an entry point, too.
+            if ii.vis == ast::Visibility::Public {
+                self.roots.insert(fd.id);
+            }
+
             if let Some(decl) = fd.declaration {
                 if is_local(decl) {
                     // If we're implementing a method in the local crate, record
@@ -305,6 +804,8 @@ impl<'v, 'l, 'tcx: 'l> visit::Visitor<'v> for RecordVisitor<'l, 'tcx> {
 pub enum CallKind {
     Definite,
     Potential,
+    // Recovered from macro-generated code; see `track_macro_calls`.
+    Macro,
 }
 
 // An edge in the callgraph, only used with graphviz.
@@ -325,23 +826,81 @@ impl<'a, 'l, 'tcx: 'l> Labeller<'a, NodeId, Edge> for RecordVisitor<'l, 'tcx> {
         graphviz::LabelText::label(&*self.functions[n])
     }
 
-    // TODO styles
+    // Fill nodes that participate in a recursive cycle so they stand out.
+    fn node_style(&'a self, n: &NodeId) -> Style {
+        if self.cycle_nodes().contains(n) {
+            Style::Filled
+        } else {
+            Style::None
+        }
+    }
+
+    fn node_color(&'a self, n: &NodeId) -> Option<graphviz::LabelText<'a>> {
+        if self.cycle_nodes().contains(n) {
+            Some(graphviz::LabelText::label("red"))
+        } else if !self.hide_dead_code && !self.reachable_all().contains(n) {
+            // Dim (rather than omit) dead code so the live structure still
+            // stands out without losing the full picture.
+            Some(graphviz::LabelText::label("gray"))
+        } else {
+            None
+        }
+    }
+
+    // Definite calls are solid, potential (dynamic dispatch) calls are
+    // dotted, and calls recovered from macro expansions are dashed.
+    fn edge_style(&'a self, e: &Edge) -> Style {
+        match e.2 {
+            CallKind::Definite => Style::Solid,
+            CallKind::Potential => Style::Dotted,
+            CallKind::Macro => Style::Dashed,
+        }
+    }
+
+    fn edge_color(&'a self, e: &Edge) -> Option<graphviz::LabelText<'a>> {
+        match e.2 {
+            CallKind::Macro => Some(graphviz::LabelText::label("blue")),
+            _ => None,
+        }
+    }
 }
 
 // Drives the graphviz visualisation.
 impl<'a, 'l, 'tcx: 'l> GraphWalk<'a, NodeId, Edge> for RecordVisitor<'l, 'tcx> {
     fn nodes(&'a self) -> graphviz::Nodes<'a, NodeId> {
-        graphviz::Nodes::from_iter(self.functions.keys().cloned())
+        let ids: Vec<NodeId> = if self.hide_dead_code {
+            let reachable = self.reachable_all();
+            self.functions.keys().cloned().filter(|id| reachable.contains(id)).collect()
+        } else {
+            self.functions.keys().cloned().collect()
+        };
+        graphviz::Nodes::from_iter(ids)
     }
 
     fn edges(&'a self) -> graphviz::Edges<'a, Edge> {
-        let static_iter = self.static_calls.iter().map(|&(ref f, ref t)| (f.clone(),
-                                                                          t.clone(),
-                                                                          CallKind::Definite));
-        let dyn_iter = self.dynamic_calls.iter().map(|&(ref f, ref t)| (f.clone(),
-                                                                        t.clone(),
-                                                                        CallKind::Potential));
-        graphviz::Edges::from_iter(static_iter.chain(dyn_iter))
+        // When hiding dead code, also drop edges touching an omitted node.
+        let live = if self.hide_dead_code {
+            Some(self.reachable_all())
+        } else {
+            None
+        };
+        let keep = |f: &NodeId, t: &NodeId| {
+            match live {
+                Some(ref live) => live.contains(f) && live.contains(t),
+                None => true,
+            }
+        };
+
+        let static_iter = self.static_calls.iter()
+                              .filter(|&&(ref f, ref t)| keep(f, t))
+                              .map(|&(ref f, ref t)| (f.clone(), t.clone(), CallKind::Definite));
+        let dyn_iter = self.dynamic_calls.iter()
+                           .filter(|&&(ref f, ref t)| keep(f, t))
+                           .map(|&(ref f, ref t)| (f.clone(), t.clone(), CallKind::Potential));
+        let macro_iter = self.macro_calls.iter()
+                             .filter(|&&(ref f, ref t)| keep(f, t))
+                             .map(|&(ref f, ref t)| (f.clone(), t.clone(), CallKind::Macro));
+        graphviz::Edges::from_iter(static_iter.chain(dyn_iter).chain(macro_iter).collect::<Vec<_>>())
     }
 
     fn source(&'a self, &(from, _, _): &Edge) -> NodeId {